@@ -0,0 +1,348 @@
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+};
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    Server(String),
+    Invalid,
+    MaxRetriesExceeded,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "io error: {e}"),
+            ClientError::Server(reply) => write!(f, "server error: {reply}"),
+            ClientError::Invalid => write!(f, "server rejected the command as invalid"),
+            ClientError::MaxRetriesExceeded => write!(f, "max reconnect attempts exceeded"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// Encodes a single value (not its key) using the length-prefixed,
+/// binary-safe framing so arbitrary bytes survive the round trip.
+fn encode_framed(value: &[u8]) -> Vec<u8> {
+    let mut out = format!("${}\r\n", value.len()).into_bytes();
+    out.extend_from_slice(value);
+    out
+}
+
+/// The error `read_reply` reports when the server's side of the socket is
+/// already closed — a clean EOF, not an `io::Error` — so a request that was
+/// never actually served can't be mistaken for a successful empty reply.
+fn connection_closed() -> ClientError {
+    ClientError::Io(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "server closed the connection",
+    ))
+}
+
+/// Reads one reply: either a length-prefixed, binary-safe value (`$<len>\r\n
+/// <len bytes>`, mirroring the request-side framing in `encode_framed`) or a
+/// plain `\n`-terminated status line. Maps the well-known status replies
+/// (`ERR`, `INV`) to a typed error. Generic over `R` (rather than hardwired
+/// to `BufReader<TcpStream>`) so the parsing logic can be exercised in tests
+/// against an in-memory `Cursor`, not just a live socket.
+///
+/// A connection that has also issued `SUB` can have pub/sub pushes
+/// (`MSG <subject>\r\n$<len>\r\n<payload>`) interleaved with ordinary
+/// replies on the same byte stream. Those are skipped here rather than
+/// this crate having any way to pair them back up with a command, since
+/// this client doesn't expose a subscribe API — without this, a push that
+/// lands ahead of a pending reply would otherwise be misread as the
+/// answer to an unrelated `GET`/`INCR`/etc.
+fn read_reply<R: Read + BufRead>(reader: &mut R) -> Result<Vec<u8>> {
+    loop {
+        let first_byte = *reader.fill_buf()?.first().ok_or_else(connection_closed)?;
+
+        if first_byte == b'$' {
+            return read_framed_reply(reader);
+        }
+
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(connection_closed());
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        if line.starts_with("MSG ") {
+            read_framed_reply(reader)?;
+            continue;
+        }
+
+        return match line {
+            "ERR" => Err(ClientError::Server(line.to_string())),
+            "INV" => Err(ClientError::Invalid),
+            _ => Ok(line.as_bytes().to_vec()),
+        };
+    }
+}
+
+/// Reads the `$<len>\r\n` header already known to be present, then the
+/// exact `len` bytes that follow it, so a value containing `\n` or
+/// non-UTF-8 bytes comes back intact instead of being truncated or
+/// rejected by a line-oriented read.
+fn read_framed_reply<R: Read + BufRead>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut header = Vec::new();
+    if reader.read_until(b'\n', &mut header)? == 0 {
+        return Err(connection_closed());
+    }
+    let len: usize = std::str::from_utf8(&header)
+        .ok()
+        .and_then(|s| s.trim_end().strip_prefix('$'))
+        .and_then(|s| s.parse().ok())
+        .ok_or(ClientError::Invalid)?;
+
+    let mut value = vec![0u8; len];
+    reader.read_exact(&mut value)?;
+    Ok(value)
+}
+
+/// A request/response client: every command blocks until the server's reply
+/// has been read, and a broken pipe triggers a reconnect-and-retry rather
+/// than bubbling straight up.
+pub trait SyncClient {
+    /// Writes `command` and reads the reply. A connection failure while
+    /// writing means the server never saw the command, so it's safe to
+    /// transparently reconnect and resend, up to the configured number of
+    /// attempts. A connection failure while *reading* the reply is not
+    /// retried, even for the same kinds of errors: the command may already
+    /// have reached and been processed by the server, and silently
+    /// resending it could run a non-idempotent command (e.g. `incr`) twice.
+    /// That failure is surfaced to the caller instead. The reply is
+    /// returned as raw bytes so a `GET` of a binary value round-trips
+    /// intact; callers that expect text (`incr`, `list`) convert it
+    /// themselves.
+    fn send_and_confirm(&mut self, command: &[u8]) -> Result<Vec<u8>>;
+
+    fn get(&mut self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.send_and_confirm(format!("get {key}\n").as_bytes())? {
+            reply if reply == b"NIL" => Ok(None),
+            reply => Ok(Some(reply)),
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        let mut command = format!("set {key} ").into_bytes();
+        command.extend_from_slice(&encode_framed(value));
+        command.push(b'\n');
+        self.send_and_confirm(&command).map(|_| ())
+    }
+
+    fn mset(&mut self, pairs: &[(&str, &[u8])]) -> Result<()> {
+        let mut command = b"mset ".to_vec();
+        for (i, (key, value)) in pairs.iter().enumerate() {
+            if i > 0 {
+                command.push(b' ');
+            }
+            command.extend_from_slice(key.as_bytes());
+            command.push(b' ');
+            command.extend_from_slice(&encode_framed(value));
+        }
+        command.push(b'\n');
+        self.send_and_confirm(&command).map(|_| ())
+    }
+
+    fn incr(&mut self, key: &str) -> Result<isize> {
+        let reply = self.send_and_confirm(format!("incr {key}\n").as_bytes())?;
+        let text = String::from_utf8_lossy(&reply).into_owned();
+        text.parse().map_err(|_| ClientError::Server(text))
+    }
+
+    fn list(&mut self) -> Result<Vec<String>> {
+        let reply = self.send_and_confirm(b"list\n")?;
+        let text = String::from_utf8_lossy(&reply);
+        Ok(text.split(' ').filter(|s| !s.is_empty()).map(str::to_string).collect())
+    }
+}
+
+/// A fire-and-forget client: `send` writes the command and returns as soon
+/// as the bytes are on the wire, without waiting for the server's reply.
+/// Intended for pipelines of `SET`/`INCR` calls where the caller doesn't
+/// need (or want to pay the latency for) a per-command acknowledgement.
+pub trait AsyncClient {
+    fn send(&mut self, command: &[u8]) -> Result<()>;
+
+    fn set(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        let mut command = format!("set {key} ").into_bytes();
+        command.extend_from_slice(&encode_framed(value));
+        command.push(b'\n');
+        self.send(&command)
+    }
+
+    fn incr(&mut self, key: &str) -> Result<()> {
+        self.send(format!("incr {key}\n").as_bytes())
+    }
+}
+
+/// The default TCP-backed client, implementing both [`SyncClient`] and
+/// [`AsyncClient`] over the same connection.
+pub struct TcpClient {
+    addr: String,
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    max_attempts: u32,
+}
+
+impl TcpClient {
+    pub fn connect(addr: impl Into<String>) -> Result<Self> {
+        Self::connect_with_retries(addr, 3)
+    }
+
+    pub fn connect_with_retries(addr: impl Into<String>, max_attempts: u32) -> Result<Self> {
+        let addr = addr.into();
+        let stream = TcpStream::connect(&addr)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(TcpClient {
+            addr,
+            stream,
+            reader,
+            max_attempts,
+        })
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        let stream = TcpStream::connect(&self.addr)?;
+        self.reader = BufReader::new(stream.try_clone()?);
+        self.stream = stream;
+        Ok(())
+    }
+
+    /// True for any error that means the connection itself is no good
+    /// anymore — a broken pipe on write, or the clean EOF `read_reply` sees
+    /// once the server has already closed its end — as opposed to an error
+    /// about the command itself (`ERR`, `INV`).
+    fn is_connection_error(err: &ClientError) -> bool {
+        matches!(
+            err,
+            ClientError::Io(e)
+                if e.kind() == io::ErrorKind::BrokenPipe || e.kind() == io::ErrorKind::UnexpectedEof
+        )
+    }
+}
+
+impl SyncClient for TcpClient {
+    fn send_and_confirm(&mut self, command: &[u8]) -> Result<Vec<u8>> {
+        for attempt in 0..self.max_attempts {
+            match self.stream.write_all(command) {
+                Ok(()) => {
+                    return match read_reply(&mut self.reader) {
+                        Ok(reply) => Ok(reply),
+                        Err(e) => {
+                            // The command already reached the server, so
+                            // resending it here could duplicate a
+                            // non-idempotent effect. Reconnect so the
+                            // connection is usable again for whatever the
+                            // caller does next, but surface this failure
+                            // rather than retrying it.
+                            if Self::is_connection_error(&e) {
+                                let _ = self.reconnect();
+                            }
+                            Err(e)
+                        }
+                    };
+                }
+                Err(e) => {
+                    let e = ClientError::from(e);
+                    if !Self::is_connection_error(&e) {
+                        return Err(e);
+                    }
+                    if attempt + 1 == self.max_attempts {
+                        return Err(ClientError::MaxRetriesExceeded);
+                    }
+                    self.reconnect()?;
+                }
+            }
+        }
+
+        Err(ClientError::MaxRetriesExceeded)
+    }
+}
+
+impl AsyncClient for TcpClient {
+    fn send(&mut self, command: &[u8]) -> Result<()> {
+        self.stream.write_all(command).map_err(ClientError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_encode_framed() {
+        assert_eq!(encode_framed(b"value"), b"$5\r\nvalue".to_vec());
+        assert_eq!(encode_framed(b""), b"$0\r\n".to_vec());
+    }
+
+    #[test]
+    fn test_read_reply_status_line() {
+        assert_eq!(
+            read_reply(&mut Cursor::new(b"OK\n")).unwrap(),
+            b"OK".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_read_reply_nil() {
+        assert_eq!(
+            read_reply(&mut Cursor::new(b"NIL\n")).unwrap(),
+            b"NIL".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_read_reply_err() {
+        match read_reply(&mut Cursor::new(b"ERR\n")) {
+            Err(ClientError::Server(reply)) => assert_eq!(reply, "ERR"),
+            other => panic!("expected ClientError::Server, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_read_reply_inv() {
+        assert!(matches!(
+            read_reply(&mut Cursor::new(b"INV\n")),
+            Err(ClientError::Invalid)
+        ));
+    }
+
+    #[test]
+    fn test_read_reply_framed_value() {
+        assert_eq!(
+            read_reply(&mut Cursor::new(b"$5\r\nhe\nlo")).unwrap(),
+            b"he\nlo".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_read_reply_skips_pubsub_pushes() {
+        // A push for "foo.bar" lands ahead of the reply we're actually
+        // waiting on; it must be skipped rather than mistaken for it.
+        let mut stream = Cursor::new(b"MSG foo.bar\r\n$5\r\nhe\nlo$3\r\nfoo".to_vec());
+        assert_eq!(read_reply(&mut stream).unwrap(), b"foo".to_vec());
+    }
+
+    #[test]
+    fn test_read_reply_eof_is_connection_closed() {
+        match read_reply(&mut Cursor::new(b"")) {
+            Err(ClientError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            other => panic!("expected ClientError::Io(UnexpectedEof), got {other:?}"),
+        }
+    }
+}
@@ -1,37 +1,69 @@
 use nom::{
     branch::alt,
-    bytes::complete::{tag, tag_no_case},
-    character::complete::alphanumeric1,
+    bytes::streaming::{tag, tag_no_case, take, take_while1},
+    character::streaming::{alphanumeric1, digit1},
+    combinator::map_res,
     multi::separated_list1,
-    sequence::separated_pair,
+    sequence::{preceded, separated_pair},
     IResult,
 };
 
 #[derive(Debug, PartialEq)]
 pub struct KeyValue<'a> {
-    pub key: &'a str,
-    pub value: &'a str,
+    pub key: &'a [u8],
+    pub value: Vec<u8>,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum Command<'a> {
-    Get(&'a str),
+    Get(&'a [u8]),
     Set(KeyValue<'a>),
     MSet(Vec<KeyValue<'a>>),
-    Increment(&'a str),
+    Increment(&'a [u8]),
     List,
     Exit,
+    Subscribe(&'a [u8]),
+    Publish { subject: &'a [u8], payload: Vec<u8> },
 }
 
-fn parse_key(input: &str) -> IResult<&str, &str> {
+fn parse_key(input: &[u8]) -> IResult<&[u8], &[u8]> {
     alphanumeric1(input)
 }
 
-fn parse_value(input: &str) -> IResult<&str, &str> {
-    alphanumeric1(input)
+/// A dot-separated subject, e.g. `foo.bar.baz`. Subscribe patterns may also
+/// contain the `*` and `>` wildcard tokens; `subject_matches` in the pubsub
+/// module is what actually tells a pattern from a concrete subject.
+fn parse_subject(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    take_while1(|b: u8| b.is_ascii_alphanumeric() || b == b'.' || b == b'*' || b == b'>')(input)
+}
+
+/// A plain, space-delimited value: `alphanumeric1`, unchanged from the
+/// original grammar.
+fn parse_plain_value(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
+    let (input, value) = alphanumeric1(input)?;
+    Ok((input, value.to_vec()))
+}
+
+/// A length-prefixed, binary-safe value of the form `$<len>\r\n<len bytes>`,
+/// so values can contain spaces, newlines, or arbitrary bytes.
+fn parse_framed_value(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
+    let (input, len) = preceded(
+        tag("$"),
+        map_res(digit1, |digits: &[u8]| {
+            std::str::from_utf8(digits).unwrap().parse::<usize>()
+        }),
+    )(input)?;
+    let (input, _) = tag("\r\n")(input)?;
+    let (input, bytes) = take(len)(input)?;
+    Ok((input, bytes.to_vec()))
 }
 
-fn parse_set(input: &str) -> IResult<&str, Command> {
+/// Accepts either grammar, auto-detecting on the leading `$`.
+fn parse_value(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
+    alt((parse_framed_value, parse_plain_value))(input)
+}
+
+fn parse_set(input: &[u8]) -> IResult<&[u8], Command> {
     let (input, _) = tag_no_case("set ")(input)?;
     let (input, key) = parse_key(input)?;
     let (input, _) = tag(" ")(input)?;
@@ -39,42 +71,78 @@ fn parse_set(input: &str) -> IResult<&str, Command> {
     Ok((input, Command::Set(KeyValue { key, value })))
 }
 
-fn parse_mset(input: &str) -> IResult<&str, Command> {
+fn parse_mset(input: &[u8]) -> IResult<&[u8], Command> {
     let (input, _) = tag_no_case("mset ")(input)?;
     let (input, key_values) =
         separated_list1(tag(" "), separated_pair(parse_key, tag(" "), parse_value))(input)?;
 
     let x = key_values
-        .iter()
+        .into_iter()
         .map(|(key, value)| KeyValue { key, value })
         .collect::<Vec<KeyValue>>();
 
     Ok((input, Command::MSet(x)))
 }
 
-fn parse_get(input: &str) -> IResult<&str, Command> {
+fn parse_get(input: &[u8]) -> IResult<&[u8], Command> {
     let (input, _) = tag_no_case("get ")(input)?;
     let (input, key) = parse_key(input)?;
     Ok((input, Command::Get(key)))
 }
 
-fn parse_increment(input: &str) -> IResult<&str, Command> {
+fn parse_increment(input: &[u8]) -> IResult<&[u8], Command> {
     let (input, _) = tag_no_case("incr ")(input)?;
     let (input, key) = parse_key(input)?;
     Ok((input, Command::Increment(key)))
 }
 
-fn parse_list(input: &str) -> IResult<&str, Command> {
+fn parse_subscribe(input: &[u8]) -> IResult<&[u8], Command> {
+    let (input, _) = tag_no_case("sub ")(input)?;
+    let (input, subject) = parse_subject(input)?;
+    Ok((input, Command::Subscribe(subject)))
+}
+
+fn parse_publish(input: &[u8]) -> IResult<&[u8], Command> {
+    let (input, _) = tag_no_case("pub ")(input)?;
+    let (input, subject) = parse_subject(input)?;
+    let (input, _) = tag(" ")(input)?;
+    let (input, payload) = parse_value(input)?;
+    Ok((input, Command::Publish { subject, payload }))
+}
+
+fn parse_list(input: &[u8]) -> IResult<&[u8], Command> {
     let (input, _) = tag_no_case("list")(input)?;
     Ok((input, Command::List))
 }
 
-fn parse_exit(input: &str) -> IResult<&str, Command> {
+fn parse_exit(input: &[u8]) -> IResult<&[u8], Command> {
     let (input, _) = tag_no_case("exit")(input)?;
     Ok((input, Command::Exit))
 }
 
-pub fn parse_command(input: &str) -> Option<Command> {
+#[derive(Debug, PartialEq)]
+pub enum ParseOutcome<'a> {
+    /// A full command was parsed; the `usize` is how many bytes of `input`
+    /// it consumed, so callers with a framed value that may itself contain
+    /// `\n` can advance past exactly the command rather than guessing at a
+    /// line boundary.
+    Complete(Command<'a>, usize),
+    /// `input` is a valid prefix of some command, but not enough of it has
+    /// arrived yet to know where it ends. Wait for more bytes and retry
+    /// rather than treating this as a bad command.
+    Incomplete,
+    /// `input` cannot be the start of any valid command, no matter what
+    /// bytes follow.
+    Invalid,
+}
+
+/// Parses one command from the front of `input`. Every sub-parser is built
+/// on `nom`'s `streaming` combinators rather than `complete`, so a frame
+/// that has only partially arrived over the socket reports `Incomplete`
+/// instead of being misread as a short, valid command (e.g. `parse_get`
+/// must not treat `b"get ke"` as `Command::Get(b"ke")` just because a TCP
+/// read happened to stop mid-key).
+pub fn parse_command(input: &[u8]) -> ParseOutcome {
     let mut parser = alt((
         parse_get,
         parse_set,
@@ -82,11 +150,14 @@ pub fn parse_command(input: &str) -> Option<Command> {
         parse_increment,
         parse_list,
         parse_exit,
+        parse_subscribe,
+        parse_publish,
     ));
 
     match parser(input) {
-        Ok((_, command)) => Some(command),
-        _ => None,
+        Ok((rest, command)) => ParseOutcome::Complete(command, input.len() - rest.len()),
+        Err(nom::Err::Incomplete(_)) => ParseOutcome::Incomplete,
+        Err(_) => ParseOutcome::Invalid,
     }
 }
 
@@ -96,18 +167,42 @@ mod tests {
 
     #[test]
     fn test_parse_get() {
-        assert_eq!(parse_get("get key"), Ok(("", Command::Get("key"))));
+        assert_eq!(
+            parse_get(b"get key\n"),
+            Ok((&b"\n"[..], Command::Get(b"key")))
+        );
+    }
+
+    #[test]
+    fn test_parse_get_incomplete() {
+        // Nothing after the key yet, so a streaming parse can't tell whether
+        // "ke" is the whole key or just the first two bytes of a longer one.
+        assert!(parse_get(b"get ke").unwrap_err().is_incomplete());
     }
 
     #[test]
     fn test_parse_set() {
         assert_eq!(
-            parse_set("set key value"),
+            parse_set(b"set key value\n"),
+            Ok((
+                &b"\n"[..],
+                Command::Set(KeyValue {
+                    key: b"key",
+                    value: b"value".to_vec(),
+                })
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_set_framed_value() {
+        assert_eq!(
+            parse_set(b"set key $5\r\nva lu"),
             Ok((
-                "",
+                &b""[..],
                 Command::Set(KeyValue {
-                    key: "key",
-                    value: "value"
+                    key: b"key",
+                    value: b"va lu".to_vec(),
                 })
             ))
         );
@@ -116,17 +211,17 @@ mod tests {
     #[test]
     fn test_parse_mset() {
         assert_eq!(
-            parse_mset("mset key1 value1 key2 value2"),
+            parse_mset(b"mset key1 value1 key2 value2\n"),
             Ok((
-                "",
+                &b"\n"[..],
                 Command::MSet(vec![
                     KeyValue {
-                        key: "key1",
-                        value: "value1"
+                        key: b"key1",
+                        value: b"value1".to_vec(),
                     },
                     KeyValue {
-                        key: "key2",
-                        value: "value2"
+                        key: b"key2",
+                        value: b"value2".to_vec(),
                     }
                 ])
             ))
@@ -136,61 +231,131 @@ mod tests {
     #[test]
     fn test_parse_increment() {
         assert_eq!(
-            parse_increment("incr key"),
-            Ok(("", Command::Increment("key")))
+            parse_increment(b"incr key\n"),
+            Ok((&b"\n"[..], Command::Increment(b"key")))
         );
     }
 
     #[test]
     fn test_parse_list() {
-        assert_eq!(parse_list("list"), Ok(("", Command::List)));
+        assert_eq!(parse_list(b"list"), Ok((&b""[..], Command::List)));
     }
 
     #[test]
     fn test_parse_exit() {
-        assert_eq!(parse_exit("exit"), Ok(("", Command::Exit)));
+        assert_eq!(parse_exit(b"exit"), Ok((&b""[..], Command::Exit)));
     }
 
     #[test]
-    fn test_parse_command() {
-        assert_eq!(parse_command("get key"), Some(Command::Get("key")));
-        assert_eq!(
-            parse_command("set key value"),
-            Some(Command::Set(KeyValue {
-                key: "key",
-                value: "value"
-            }))
-        );
-        assert_eq!(
-            parse_command("mset key1 value1 key2 value2"),
-            Some(Command::MSet(vec![
-                KeyValue {
-                    key: "key1",
-                    value: "value1"
-                },
-                KeyValue {
-                    key: "key2",
-                    value: "value2"
+    fn test_parse_subscribe() {
+        assert_eq!(
+            parse_subscribe(b"sub foo.*\n"),
+            Ok((&b"\n"[..], Command::Subscribe(b"foo.*")))
+        );
+    }
+
+    #[test]
+    fn test_parse_publish() {
+        assert_eq!(
+            parse_publish(b"pub foo.bar hello\n"),
+            Ok((
+                &b"\n"[..],
+                Command::Publish {
+                    subject: b"foo.bar",
+                    payload: b"hello".to_vec(),
                 }
-            ]))
+            ))
         );
-        assert_eq!(parse_command("incr key"), Some(Command::Increment("key")));
-        assert_eq!(parse_command("list"), Some(Command::List));
-        assert_eq!(parse_command("exit"), Some(Command::Exit));
-        assert_eq!(parse_command("invalid"), None);
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            parse_command(b"get key\n"),
+            ParseOutcome::Complete(Command::Get(b"key"), 7)
+        );
+        assert_eq!(
+            parse_command(b"set key value\n"),
+            ParseOutcome::Complete(
+                Command::Set(KeyValue {
+                    key: b"key",
+                    value: b"value".to_vec(),
+                }),
+                13
+            )
+        );
+        assert_eq!(
+            parse_command(b"mset key1 value1 key2 value2\n"),
+            ParseOutcome::Complete(
+                Command::MSet(vec![
+                    KeyValue {
+                        key: b"key1",
+                        value: b"value1".to_vec(),
+                    },
+                    KeyValue {
+                        key: b"key2",
+                        value: b"value2".to_vec(),
+                    }
+                ]),
+                28
+            )
+        );
+        assert_eq!(
+            parse_command(b"incr key\n"),
+            ParseOutcome::Complete(Command::Increment(b"key"), 8)
+        );
+        assert_eq!(
+            parse_command(b"list"),
+            ParseOutcome::Complete(Command::List, 4)
+        );
+        assert_eq!(
+            parse_command(b"exit"),
+            ParseOutcome::Complete(Command::Exit, 4)
+        );
+        assert_eq!(parse_command(b"invalid"), ParseOutcome::Invalid);
+    }
+
+    #[test]
+    fn test_parse_command_with_trailing_bytes() {
+        assert_eq!(
+            parse_command(b"get key\nget other"),
+            ParseOutcome::Complete(Command::Get(b"key"), 7)
+        );
+    }
+
+    #[test]
+    fn test_parse_command_incomplete() {
+        // The key hasn't fully arrived yet: waiting for more bytes is the
+        // right call, not guessing that "ke" is the whole key.
+        assert_eq!(parse_command(b"get ke"), ParseOutcome::Incomplete);
+        // The length-prefix header arrived but none of the framed payload
+        // has, so this must not be misread as a bare `\n`-terminated line.
+        assert_eq!(parse_command(b"set key $5\r\n"), ParseOutcome::Incomplete);
     }
 
     #[test]
     fn test_parse_command_case_insensitive() {
-        assert_eq!(parse_command("GEt key"), Some(Command::Get("key")));
         assert_eq!(
-            parse_command("SeT key value"),
-            Some(Command::Set(KeyValue {
-                key: "key",
-                value: "value"
-            }))
+            parse_command(b"GEt key\n"),
+            ParseOutcome::Complete(Command::Get(b"key"), 7)
+        );
+        assert_eq!(
+            parse_command(b"SeT key value\n"),
+            ParseOutcome::Complete(
+                Command::Set(KeyValue {
+                    key: b"key",
+                    value: b"value".to_vec(),
+                }),
+                13
+            )
+        );
+        assert_eq!(
+            parse_command(b"LIST"),
+            ParseOutcome::Complete(Command::List, 4)
+        );
+        assert_eq!(
+            parse_command(b"eXIT"),
+            ParseOutcome::Complete(Command::Exit, 4)
         );
-        assert_eq!(parse_command("LIST"), Some(Command::List));
-        assert_eq!(parse_command("eXIT"), Some(Command::Exit));
     }
 }
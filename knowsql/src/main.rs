@@ -1,77 +1,348 @@
 mod config;
+mod pubsub;
+mod shard;
 
 use std::{
-    io::{BufRead, Write},
-    net::{TcpListener, TcpStream},
-    path::PathBuf,
+    collections::HashMap,
+    io::{ErrorKind, Read, Write},
     sync::{Arc, Mutex},
 };
 
-use knowsql_bitcask::BitCask;
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Token};
 
-use knowsql_parser::{parse_command, Command, KeyValue};
+use knowsql_parser::{parse_command, Command, KeyValue, ParseOutcome};
+use pubsub::SubscriptionRegistry;
+use shard::ShardedStore;
+
+const LISTENER: Token = Token(0);
+
+struct Connection {
+    stream: TcpStream,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    closing: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Self {
+        Connection {
+            stream,
+            read_buf: Vec::new(),
+            write_buf: Vec::new(),
+            closing: false,
+        }
+    }
+}
 
 fn main() {
     let config = config::get_config();
 
-    let bitcask = {
-        let cask = BitCask::open(PathBuf::from(&config.data_dir)).unwrap();
-        let mutex = Mutex::new(cask);
-        Arc::new(mutex)
-    };
+    let store = Arc::new(ShardedStore::open(&config.data_dir, config.shard_count));
+
+    let subscriptions = Arc::new(Mutex::new(SubscriptionRegistry::new()));
 
     println!("Starting server on port {}", config.port);
 
-    let listener = TcpListener::bind(format!("0.0.0.0:{}", config.port)).unwrap();
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(1024);
 
-    for stream in listener.incoming() {
-        let stream = stream.unwrap();
-        let bitcask = bitcask.clone();
-        std::thread::spawn(move || handle_client(stream, bitcask));
-    }
-}
+    let mut listener =
+        TcpListener::bind(format!("0.0.0.0:{}", config.port).parse().unwrap()).unwrap();
+    poll.registry()
+        .register(&mut listener, LISTENER, Interest::READABLE)
+        .unwrap();
+
+    let mut connections: HashMap<usize, Connection> = HashMap::new();
+    let mut next_token = 1usize;
 
-fn handle_client(mut stream: TcpStream, bitcask: Arc<Mutex<BitCask>>) {
-    let mut bufreader = std::io::BufReader::new(stream.try_clone().unwrap());
     loop {
-        let mut buf = String::new();
-        bufreader.read_line(&mut buf).unwrap();
-
-        if let Some(command) = parse_command(&buf) {
-            match command {
-                Command::Get(key) => match bitcask.lock().unwrap().get(key) {
-                    Some(value) => stream.write_all((value + "\n").as_bytes()).unwrap(),
-                    None => stream.write_all(b"NIL\n").unwrap(),
-                },
-                Command::Set(KeyValue { key, value }) => {
-                    match bitcask.lock().unwrap().put(key, value) {
-                        Ok(_) => stream.write_all(b"OK\n").unwrap(),
-                        Err(_) => stream.write_all(b"ERR\n").unwrap(),
+        if let Err(e) = poll.poll(&mut events, None) {
+            eprintln!("poll failed: {e}");
+            continue;
+        }
+
+        for event in events.iter() {
+            if event.token() == LISTENER {
+                accept_connections(&listener, &mut poll, &mut connections, &mut next_token);
+                continue;
+            }
+
+            let token = event.token();
+            let mut drop_connection = false;
+            let mut deliveries = Vec::new();
+
+            if let Some(connection) = connections.get_mut(&token.0) {
+                if event.is_readable() {
+                    match read_ready(connection, &store, &subscriptions, token) {
+                        Some(pending) => deliveries = pending,
+                        None => drop_connection = true,
                     }
                 }
-                Command::List => {
-                    let keys = bitcask.lock().unwrap().list_keys().join(" ");
-                    stream.write_all((keys + "\n").as_bytes()).unwrap();
+
+                if !drop_connection && event.is_writable() && !write_ready(connection) {
+                    drop_connection = true;
                 }
-                Command::Exit => {
-                    stream.write_all(b"BYE\n").unwrap();
-                    break;
+
+                if connection.closing && connection.write_buf.is_empty() {
+                    drop_connection = true;
                 }
-                Command::Increment(key) => {
-                    let mut cask = bitcask.lock().unwrap();
-                    let value = cask.get(&key).unwrap_or("0".to_string());
-
-                    if let Ok(current_value) = value.parse::<isize>() {
-                        let new_value = (current_value + 1).to_string();
-                        cask.put(&key, &new_value).unwrap();
-                        stream.write_all((new_value + "\n").as_bytes()).unwrap();
-                    } else {
-                        stream.write_all(b"ERR\n").unwrap();
+            }
+
+            for (subscriber, payload) in deliveries {
+                if let Some(connection) = connections.get_mut(&subscriber.0) {
+                    connection.write_buf.extend_from_slice(&payload);
+                    if !reregister_interest(&mut poll, connection, subscriber) {
+                        remove_connection(&mut poll, &mut connections, &subscriptions, subscriber);
                     }
                 }
             }
-        } else {
-            stream.write_all(b"INV\n").unwrap();
+
+            if drop_connection {
+                remove_connection(&mut poll, &mut connections, &subscriptions, token);
+            } else if let Some(connection) = connections.get_mut(&token.0) {
+                if !reregister_interest(&mut poll, connection, token) {
+                    remove_connection(&mut poll, &mut connections, &subscriptions, token);
+                }
+            }
+        }
+    }
+}
+
+/// Deregisters and drops `token`'s connection, if it still has one, and
+/// clears any pub/sub subscriptions it held. Shared by the normal
+/// teardown path and by the reregistration-failure path below, since both
+/// end up wanting the same cleanup.
+fn remove_connection(
+    poll: &mut Poll,
+    connections: &mut HashMap<usize, Connection>,
+    subscriptions: &Arc<Mutex<SubscriptionRegistry>>,
+    token: Token,
+) {
+    if let Some(mut connection) = connections.remove(&token.0) {
+        let _ = poll.registry().deregister(&mut connection.stream);
+    }
+    subscriptions.lock().unwrap().remove(token);
+}
+
+/// Returns `false` (instead of panicking) if the reregistration itself
+/// fails, so one bad connection can be dropped without taking down the
+/// single-threaded reactor that every other connection shares.
+fn reregister_interest(poll: &mut Poll, connection: &mut Connection, token: Token) -> bool {
+    let interest = if connection.write_buf.is_empty() {
+        Interest::READABLE
+    } else {
+        Interest::READABLE | Interest::WRITABLE
+    };
+    poll.registry()
+        .reregister(&mut connection.stream, token, interest)
+        .is_ok()
+}
+
+fn accept_connections(
+    listener: &TcpListener,
+    poll: &mut Poll,
+    connections: &mut HashMap<usize, Connection>,
+    next_token: &mut usize,
+) {
+    loop {
+        match listener.accept() {
+            Ok((mut stream, _addr)) => {
+                let token = Token(*next_token);
+                *next_token += 1;
+                if let Err(e) = poll
+                    .registry()
+                    .register(&mut stream, token, Interest::READABLE)
+                {
+                    eprintln!("failed to register connection: {e}");
+                    continue;
+                }
+                connections.insert(token.0, Connection::new(stream));
+            }
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(e) => {
+                eprintln!("failed to accept connection: {e}");
+                break;
+            }
+        }
+    }
+}
+
+/// Drains everything currently available on the socket into `read_buf`, then
+/// parses and executes as many complete frames as it finds, returning the
+/// cross-connection pub/sub deliveries that still need to be dispatched.
+/// Returns `None` once the connection should be torn down.
+fn read_ready(
+    connection: &mut Connection,
+    store: &Arc<ShardedStore>,
+    subscriptions: &Arc<Mutex<SubscriptionRegistry>>,
+    token: Token,
+) -> Option<Vec<(Token, Vec<u8>)>> {
+    let mut chunk = [0u8; 4096];
+    loop {
+        match connection.stream.read(&mut chunk) {
+            Ok(0) => return None,
+            Ok(n) => connection.read_buf.extend_from_slice(&chunk[..n]),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => return None,
+        }
+    }
+
+    let mut deliveries = Vec::new();
+
+    loop {
+        match parse_command(&connection.read_buf) {
+            ParseOutcome::Complete(command, consumed) => {
+                connection.read_buf.drain(..consumed);
+                if connection.read_buf.first() == Some(&b'\n') {
+                    connection.read_buf.remove(0);
+                }
+                deliveries.extend(handle_command(
+                    command,
+                    store,
+                    subscriptions,
+                    token,
+                    connection,
+                ));
+            }
+            // A valid prefix of some command has arrived, but we can't yet
+            // tell where it ends (e.g. a partial key, or a framed value
+            // whose length header is in but whose payload bytes aren't).
+            // Wait for the next readiness tick instead of guessing.
+            ParseOutcome::Incomplete => break,
+            ParseOutcome::Invalid => match connection.read_buf.iter().position(|&b| b == b'\n') {
+                Some(pos) => {
+                    connection.read_buf.drain(..=pos);
+                    connection.write_buf.extend_from_slice(b"INV\n");
+                }
+                None => break,
+            },
+        }
+    }
+
+    Some(deliveries)
+}
+
+/// Flushes as much of `write_buf` as the socket will currently accept.
+/// Returns `false` if the connection is no longer writable.
+fn write_ready(connection: &mut Connection) -> bool {
+    while !connection.write_buf.is_empty() {
+        match connection.stream.write(&connection.write_buf) {
+            Ok(0) => return false,
+            Ok(n) => {
+                connection.write_buf.drain(..n);
+            }
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => return false,
+        }
+    }
+
+    true
+}
+
+/// Writes `value` to `connection`'s reply buffer using the same
+/// length-prefixed, binary-safe framing (`$<len>\r\n<bytes>`) that `SET`
+/// and `MSET` accept for their request values, so a value
+/// containing `\n` or non-UTF-8 bytes survives the trip back to the
+/// client intact.
+fn write_framed_reply(connection: &mut Connection, value: &[u8]) {
+    connection
+        .write_buf
+        .extend_from_slice(format!("${}\r\n", value.len()).as_bytes());
+    connection.write_buf.extend_from_slice(value);
+}
+
+/// Frames a pub/sub delivery as `MSG <subject>\r\n$<len>\r\n<payload>`. This
+/// is deliberately distinct from a direct command reply (which never starts
+/// with `MSG `), so a connection that is both subscribed and mid-request can
+/// tell an unsolicited push apart from the reply it's actually waiting on —
+/// see `knowsql_client::read_reply`, which skips over this tag rather than
+/// handing its bytes back as if they answered the caller's last command.
+fn build_push_message(subject: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut message = b"MSG ".to_vec();
+    message.extend_from_slice(subject);
+    message.extend_from_slice(b"\r\n");
+    message.extend_from_slice(format!("${}\r\n", payload.len()).as_bytes());
+    message.extend_from_slice(payload);
+    message
+}
+
+fn handle_command(
+    command: Command,
+    store: &Arc<ShardedStore>,
+    subscriptions: &Arc<Mutex<SubscriptionRegistry>>,
+    token: Token,
+    connection: &mut Connection,
+) -> Vec<(Token, Vec<u8>)> {
+    match command {
+        Command::Get(key) => {
+            match store.shard(key).lock().unwrap().get(key) {
+                Some(value) => write_framed_reply(connection, &value),
+                None => connection.write_buf.extend_from_slice(b"NIL\n"),
+            }
+            Vec::new()
+        }
+        Command::Set(KeyValue { key, value }) => {
+            match store.shard(key).lock().unwrap().put(key, value) {
+                Ok(_) => connection.write_buf.extend_from_slice(b"OK\n"),
+                Err(_) => connection.write_buf.extend_from_slice(b"ERR\n"),
+            }
+            Vec::new()
+        }
+        Command::MSet(pairs) => {
+            match store.mset(pairs) {
+                Ok(_) => connection.write_buf.extend_from_slice(b"OK\n"),
+                Err(_) => connection.write_buf.extend_from_slice(b"ERR\n"),
+            }
+            Vec::new()
+        }
+        Command::List => {
+            let keys = store.list_keys().join(" ");
+            write_framed_reply(connection, keys.as_bytes());
+            Vec::new()
+        }
+        Command::Exit => {
+            connection.write_buf.extend_from_slice(b"BYE\n");
+            connection.closing = true;
+            Vec::new()
+        }
+        Command::Increment(key) => {
+            let mut cask = store.shard(key).lock().unwrap();
+            let value = cask.get(key).unwrap_or_else(|| b"0".to_vec());
+            let value = String::from_utf8_lossy(&value);
+
+            if let Ok(current_value) = value.parse::<isize>() {
+                let new_value = (current_value + 1).to_string();
+                match cask.put(key, new_value.clone().into_bytes()) {
+                    Ok(_) => write_framed_reply(connection, new_value.as_bytes()),
+                    Err(_) => connection.write_buf.extend_from_slice(b"ERR\n"),
+                }
+            } else {
+                connection.write_buf.extend_from_slice(b"ERR\n");
+            }
+            Vec::new()
+        }
+        Command::Subscribe(subject) => {
+            subscriptions
+                .lock()
+                .unwrap()
+                .subscribe(token, subject.to_vec());
+            connection.write_buf.extend_from_slice(b"OK\n");
+            Vec::new()
+        }
+        Command::Publish { subject, payload } => {
+            let subscribers = subscriptions.lock().unwrap().matching_subscribers(subject);
+            connection.write_buf.extend_from_slice(b"OK\n");
+
+            let message = build_push_message(subject, &payload);
+
+            subscribers
+                .into_iter()
+                .filter(|&subscriber| subscriber != token)
+                .map(|subscriber| (subscriber, message.clone()))
+                .collect()
         }
     }
 }
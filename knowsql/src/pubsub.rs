@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+use mio::Token;
+
+/// Maps subject patterns to the connection tokens subscribed to them.
+/// Patterns are stored per connection token as a list, so a single client
+/// can subscribe to several subjects independently.
+#[derive(Default)]
+pub struct SubscriptionRegistry {
+    subscriptions: HashMap<usize, Vec<Vec<u8>>>,
+}
+
+impl SubscriptionRegistry {
+    pub fn new() -> Self {
+        SubscriptionRegistry::default()
+    }
+
+    pub fn subscribe(&mut self, token: Token, subject: Vec<u8>) {
+        self.subscriptions.entry(token.0).or_default().push(subject);
+    }
+
+    pub fn remove(&mut self, token: Token) {
+        self.subscriptions.remove(&token.0);
+    }
+
+    /// Tokens of every connection whose subscribed pattern matches `subject`.
+    pub fn matching_subscribers(&self, subject: &[u8]) -> Vec<Token> {
+        self.subscriptions
+            .iter()
+            .filter(|(_, patterns)| patterns.iter().any(|pattern| subject_matches(pattern, subject)))
+            .map(|(&token, _)| Token(token))
+            .collect()
+    }
+}
+
+/// Matches a dot-separated subject against a subscribe pattern. `*` stands
+/// in for exactly one token, `>` for one-or-more trailing tokens; any pattern
+/// tokens after a `>` are not checked.
+pub fn subject_matches(pattern: &[u8], subject: &[u8]) -> bool {
+    let pattern_tokens: Vec<&[u8]> = pattern.split(|&b| b == b'.').collect();
+    let subject_tokens: Vec<&[u8]> = subject.split(|&b| b == b'.').collect();
+
+    for (i, pattern_token) in pattern_tokens.iter().enumerate() {
+        if *pattern_token == b">" {
+            return i < subject_tokens.len();
+        }
+
+        let Some(subject_token) = subject_tokens.get(i) else {
+            return false;
+        };
+
+        if *pattern_token != b"*" && pattern_token != subject_token {
+            return false;
+        }
+    }
+
+    pattern_tokens.len() == subject_tokens.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_match() {
+        assert!(subject_matches(b"foo.bar", b"foo.bar"));
+        assert!(!subject_matches(b"foo.bar", b"foo.baz"));
+    }
+
+    #[test]
+    fn test_single_token_wildcard() {
+        assert!(subject_matches(b"foo.*", b"foo.bar"));
+        assert!(!subject_matches(b"foo.*", b"foo.bar.baz"));
+    }
+
+    #[test]
+    fn test_trailing_wildcard() {
+        assert!(subject_matches(b"foo.>", b"foo.bar"));
+        assert!(subject_matches(b"foo.>", b"foo.bar.baz"));
+        assert!(!subject_matches(b"foo.>", b"foo"));
+    }
+
+    #[test]
+    fn test_matching_subscribers() {
+        let mut registry = SubscriptionRegistry::new();
+        registry.subscribe(Token(1), b"foo.*".to_vec());
+        registry.subscribe(Token(2), b"foo.>".to_vec());
+        registry.subscribe(Token(3), b"bar.*".to_vec());
+
+        let mut matched = registry.matching_subscribers(b"foo.bar.baz");
+        matched.sort_by_key(|token| token.0);
+        assert_eq!(matched, vec![Token(2)]);
+    }
+
+    #[test]
+    fn test_remove_drops_all_subscriptions_for_token() {
+        let mut registry = SubscriptionRegistry::new();
+        registry.subscribe(Token(1), b"foo.*".to_vec());
+        registry.subscribe(Token(1), b"bar.*".to_vec());
+
+        registry.remove(Token(1));
+
+        assert!(registry.matching_subscribers(b"foo.bar").is_empty());
+        assert!(registry.matching_subscribers(b"bar.bar").is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use knowsql_bitcask::BitCask;
+use knowsql_parser::KeyValue;
+
+/// Routes keys across N independent `BitCask` instances by a stable hash of
+/// the key, so `GET`/`SET`/`INCR` on disjoint keys lock independent shards
+/// instead of serializing through one global mutex.
+pub struct ShardedStore {
+    shards: Vec<Arc<Mutex<BitCask>>>,
+}
+
+impl ShardedStore {
+    pub fn open(data_dir: &str, shard_count: usize) -> Self {
+        assert!(
+            shard_count > 0,
+            "shard_count must be greater than 0, got {shard_count}"
+        );
+
+        let shards = (0..shard_count)
+            .map(|i| {
+                let dir = PathBuf::from(data_dir).join(format!("shard-{i}"));
+                Arc::new(Mutex::new(BitCask::open(dir).unwrap()))
+            })
+            .collect();
+        ShardedStore { shards }
+    }
+
+    fn shard_index(&self, key: &[u8]) -> usize {
+        fnv1a(key) as usize % self.shards.len()
+    }
+
+    /// The single shard that owns `key`.
+    pub fn shard(&self, key: &[u8]) -> &Arc<Mutex<BitCask>> {
+        &self.shards[self.shard_index(key)]
+    }
+
+    /// Fans `LIST` out across every shard and concatenates the results.
+    pub fn list_keys(&self) -> Vec<String> {
+        self.shards
+            .iter()
+            .flat_map(|shard| shard.lock().unwrap().list_keys())
+            .collect()
+    }
+
+    /// Groups `pairs` by owning shard and locks each shard exactly once,
+    /// rather than once per pair.
+    pub fn mset(&self, pairs: Vec<KeyValue>) -> Result<(), ()> {
+        let mut by_shard: Vec<Vec<KeyValue>> = (0..self.shards.len()).map(|_| Vec::new()).collect();
+        for kv in pairs {
+            let idx = self.shard_index(kv.key);
+            by_shard[idx].push(kv);
+        }
+
+        let mut ok = true;
+        for (idx, kvs) in by_shard.into_iter().enumerate() {
+            if kvs.is_empty() {
+                continue;
+            }
+
+            let mut cask = self.shards[idx].lock().unwrap();
+            for kv in kvs {
+                if cask.put(kv.key, kv.value).is_err() {
+                    ok = false;
+                }
+            }
+        }
+
+        if ok {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+}
+
+/// FNV-1a. Only used to pick a shard, not for anything security sensitive.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fnv1a_is_stable_and_distinct() {
+        assert_eq!(fnv1a(b"key"), fnv1a(b"key"));
+        assert_ne!(fnv1a(b"key"), fnv1a(b"other"));
+    }
+
+    #[test]
+    #[should_panic(expected = "shard_count must be greater than 0")]
+    fn test_open_rejects_zero_shards() {
+        ShardedStore::open("/tmp/knowsql-test-shard-zero", 0);
+    }
+}